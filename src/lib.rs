@@ -25,6 +25,12 @@
 //! cpython-json = "0.3"
 //! ```
 //!
+//! Python `int`s larger than a 64-bit word round-trip losslessly, and `to_json_with`/
+//! `from_json_with` can be configured (see `Options`) to represent `inf`/`-inf`/`nan` as the bare `Infinity`/`-Infinity`/
+//! `NaN` tokens CPython's `json` module emits by default. Both depend on `serde_json`'s
+//! `arbitrary_precision` feature, which `cpython-json`'s own `Cargo.toml` always enables on its
+//! `serde_json` dependency — there's nothing extra to turn on downstream.
+//!
 //! Similar to `cpython`, Python 3 is used by default. To use Python 2:
 //!
 //! ```toml
@@ -70,7 +76,9 @@ extern crate serde_json;
 
 use cpython::*;
 use serde_json::value::Value;
+use std::collections::{HashMap, HashSet};
 use std::convert::From;
+use std::str::FromStr;
 
 quick_error! {
     /// The `Error` enum returned by this crate.
@@ -104,6 +112,99 @@ quick_error! {
         InvalidFloat {}
         /// The serde_json crate lied to us and a `Number` is neither u64, i64, or f64.
         ImpossibleNumber {}
+        /// Conversion recursed deeper than `Options::max_depth`.
+        RecursionLimitExceeded {}
+        /// A container (`dict`, `list`, or `tuple`) directly or indirectly contains itself, which
+        /// can't be represented as `Value` (there's no back-reference in JSON).
+        CircularReference(obj: PyObject) {}
+    }
+}
+
+/// The default maximum nesting depth `to_json`/`from_json` will recurse before giving up with
+/// `JsonError::RecursionLimitExceeded`, chosen in the spirit of jiter's `DEFAULT_RECURSION`.
+pub const DEFAULT_RECURSION_LIMIT: usize = 256;
+
+/// Options controlling conversions that don't have one single obviously-correct behavior.
+///
+/// `Options::default()` reproduces the strict, CPython-`json`-module-like behavior `to_json` and
+/// `from_json` have always had; set fields to opt into the looser behaviors described on them.
+#[derive(Clone, Copy, Debug)]
+pub struct Options {
+    /// Convert `datetime.datetime`, `datetime.date`, and `datetime.time` objects to an ISO-8601
+    /// string instead of raising `JsonError::TypeError`. When set, `from_json_with` also makes a
+    /// best-effort attempt to parse strings back into the same types via
+    /// `datetime.{datetime,date,time}.fromisoformat`, falling back to a plain string if none of
+    /// them accept it (see `parse_iso8601` for the heuristic used to pick which type to try
+    /// first). This is inherently lossy: any ordinary string that happens to look like one of
+    /// these formats (including one that started life as a plain JSON string, not a serialized
+    /// `datetime`/`date`/`time`) is silently coerced when this is enabled.
+    pub datetime_as_iso8601: bool,
+    /// Allow non-finite floats (`inf`, `-inf`, `nan`) instead of raising
+    /// `JsonError::InvalidFloat`, matching the `allow_nan=True` default CPython's `json` module
+    /// uses. `to_json_with` encodes them as the bare `Infinity`/`-Infinity`/`NaN` tokens (via
+    /// `serde_json`'s `arbitrary_precision` feature) the same way `json.dumps` does, and
+    /// `from_json_with` recognizes those same tokens coming back in.
+    pub allow_nan: bool,
+    /// Maximum nesting depth `to_json`/`from_json` will recurse before giving up with
+    /// `JsonError::RecursionLimitExceeded`. Defaults to `DEFAULT_RECURSION_LIMIT`.
+    pub max_depth: usize,
+}
+
+impl Default for Options {
+    fn default() -> Options {
+        Options {
+            datetime_as_iso8601: false,
+            allow_nan: false,
+            max_depth: DEFAULT_RECURSION_LIMIT,
+        }
+    }
+}
+
+/// Which strings `from_json_cached` should look up in its `StringCache` rather than allocate
+/// fresh, mirroring jiter's string-cache modes.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Default)]
+pub enum StringCacheMode {
+    /// Cache both `dict`/object keys and `Value::String` values.
+    All,
+    /// Cache only `dict`/object keys, which are the ones most likely to repeat across a large
+    /// array of similarly-shaped objects.
+    Keys,
+    /// Don't cache anything; behaves exactly like `from_json_with`.
+    #[default]
+    None,
+}
+
+/// A cache of interned `PyUnicode` objects, keyed by their text, used by `from_json_cached` to
+/// avoid re-allocating a `PyUnicode` for every repeated dict key or string value.
+///
+/// The cache is unbounded by default; call `cache_clear` periodically (or between payloads) to
+/// release the Python objects it's holding onto.
+#[derive(Default)]
+pub struct StringCache(HashMap<String, PyObject>);
+
+impl StringCache {
+    /// Create an empty cache.
+    pub fn new() -> StringCache {
+        StringCache(HashMap::new())
+    }
+
+    /// Drop every cached string.
+    pub fn cache_clear(&mut self) {
+        self.0.clear();
+    }
+
+    /// The number of distinct strings currently cached.
+    pub fn cache_usage(&self) -> usize {
+        self.0.len()
+    }
+
+    fn get_or_insert(&mut self, py: Python, text: &str) -> PyObject {
+        if let Some(obj) = self.0.get(text) {
+            return obj.clone_ref(py);
+        }
+        let obj = PyUnicode::new(py, text).into_object();
+        self.0.insert(text.to_string(), obj.clone_ref(py));
+        obj
     }
 }
 
@@ -155,12 +256,59 @@ impl JsonError {
                     ptraceback: None,
                 }
             }
+            JsonError::RecursionLimitExceeded => {
+                PyErr {
+                    ptype: cpython::exc::ValueError::type_object(py).into_object(),
+                    pvalue: Some(PyString::new(py, "maximum recursion depth exceeded").into_object()),
+                    ptraceback: None,
+                }
+            }
+            JsonError::CircularReference(_) => {
+                PyErr {
+                    ptype: cpython::exc::ValueError::type_object(py).into_object(),
+                    pvalue: Some(PyString::new(py, "Circular reference detected").into_object()),
+                    ptraceback: None,
+                }
+            }
         }
     }
 }
 
 /// Convert from a `cpython::PyObject` to a `serde_json::Value`.
 pub fn to_json(py: Python, obj: &PyObject) -> Result<Value, JsonError> {
+    to_json_with(py, obj, None, Options::default())
+}
+
+/// Like `to_json`, but for objects it doesn't otherwise recognize, falls back to calling
+/// `default` with the object and converting whatever it returns instead of raising
+/// `JsonError::TypeError`, and applies `options` to conversions that have more than one
+/// reasonable behavior.
+///
+/// The `default` callback mirrors the `default` keyword argument taken by Python's `json.dumps`:
+/// it's tried once per unconvertible object, its return value is converted recursively (so
+/// `default` may itself return another object `default` needs to handle), and `TypeError` is only
+/// raised when no `default` is given. An exception raised by `default` is returned as
+/// `JsonError::PythonError`, and a `default` that keeps returning something unconvertible ends in
+/// `JsonError::RecursionLimitExceeded` once `options.max_depth` is passed.
+pub fn to_json_with(py: Python,
+                     obj: &PyObject,
+                     default: Option<&PyObject>,
+                     options: Options)
+                     -> Result<Value, JsonError> {
+    to_json_inner(py, obj, default, options, 0, &mut HashSet::new())
+}
+
+fn to_json_inner(py: Python,
+                  obj: &PyObject,
+                  default: Option<&PyObject>,
+                  options: Options,
+                  depth: usize,
+                  seen: &mut HashSet<usize>)
+                  -> Result<Value, JsonError> {
+    if depth > options.max_depth {
+        return Err(JsonError::RecursionLimitExceeded);
+    }
+
     macro_rules! cast {
         ($t:ty, $f:expr) => {
             if let Ok(val) = obj.cast_as::<$t>(py) {
@@ -177,7 +325,23 @@ pub fn to_json(py: Python, obj: &PyObject) -> Result<Value, JsonError> {
         }
     }
 
+    // `dict`/`list`/`tuple` are the only containers that can recurse, so only they need to be
+    // tracked for circular references; `id()` doubles as a cheap, collision-free marker of "this
+    // container is one of our own ancestors".
+    macro_rules! guard_against_cycle {
+        () => {
+            {
+                let id = obj.as_ptr() as usize;
+                if !seen.insert(id) {
+                    return Err(JsonError::CircularReference(obj.clone_ref(py)));
+                }
+                id
+            }
+        }
+    }
+
     cast!(PyDict, |x: &PyDict| {
+        let id = guard_against_cycle!();
         let mut map = serde_json::Map::new();
         for (key_obj, value) in x.items(py) {
             let key = if key_obj == py.None() {
@@ -193,72 +357,255 @@ pub fn to_json(py: Python, obj: &PyObject) -> Result<Value, JsonError> {
             } else {
                 Err(JsonError::DictKeyNotString(key_obj))
             };
-            map.insert(key?, to_json(py, &value)?);
+            map.insert(key?, to_json_inner(py, &value, default, options, depth + 1, seen)?);
         }
+        seen.remove(&id);
         Ok(Value::Object(map))
     });
 
     cast!(PyList,
-          |x: &PyList| Ok(Value::Array(try!(x.iter(py).map(|x| to_json(py, &x)).collect()))));
+          |x: &PyList| {
+        let id = guard_against_cycle!();
+        let result: Result<Vec<_>, _> = x.iter(py)
+            .map(|x| to_json_inner(py, &x, default, options, depth + 1, seen))
+            .collect();
+        seen.remove(&id);
+        result.map(Value::Array)
+    });
     cast!(PyTuple,
-          |x: &PyTuple| Ok(Value::Array(try!(x.iter(py).map(|x| to_json(py, x)).collect()))));
+          |x: &PyTuple| {
+        let id = guard_against_cycle!();
+        let result: Result<Vec<_>, _> = x.iter(py)
+            .map(|x| to_json_inner(py, x, default, options, depth + 1, seen))
+            .collect();
+        seen.remove(&id);
+        result.map(Value::Array)
+    });
 
     extract!(String);
     extract!(bool);
 
-    cast!(PyFloat,
-          |x: &PyFloat| match serde_json::Number::from_f64(x.value(py)) {
-              Some(n) => Ok(Value::Number(n)),
-              None => Err(JsonError::InvalidFloat),
-          });
+    cast!(PyFloat, |x: &PyFloat| {
+        let value = x.value(py);
+        match serde_json::Number::from_f64(value) {
+            Some(n) => Ok(Value::Number(n)),
+            None if options.allow_nan => {
+                let token = if value.is_nan() {
+                    "NaN"
+                } else if value.is_sign_negative() {
+                    "-Infinity"
+                } else {
+                    "Infinity"
+                };
+                // `Number::from_str` parses its input as a JSON number, and none of these bare
+                // tokens are valid JSON number syntax — `arbitrary_precision` only changes how a
+                // number already accepted by that grammar is stored, it doesn't relax the
+                // grammar itself, so `from_str` rejects all three. `from_string_unchecked` skips
+                // that validation and stores the token as-is, which is exactly what's needed to
+                // reproduce the non-standard output `json.dumps(allow_nan=True)` produces.
+                Ok(Value::Number(serde_json::Number::from_string_unchecked(token.to_owned())))
+            }
+            None => Err(JsonError::InvalidFloat),
+        }
+    });
 
     extract!(u64);
     extract!(i64);
 
+    // `u64`/`i64` cover every `int` CPython itself would fit in a machine word, but Python ints
+    // are arbitrary precision. Fall back to the decimal `str()` of the object and let
+    // `serde_json`'s `arbitrary_precision` feature keep every digit intact.
+    //
+    // Python 3's `int` is `PyLong` (rust-cpython only exposes `PyInt` under the `python27-sys`
+    // feature, for Python 2's separate `int` type), so `PyLong` is the cast that actually matches
+    // on the default build.
+    cast!(PyLong, |_| {
+        let text = obj.str(py)?.to_string(py)?.into_owned();
+        serde_json::Number::from_str(&text)
+            .map(Value::Number)
+            .map_err(JsonError::SerdeJsonError)
+    });
+
     if obj == &py.None() {
         return Ok(Value::Null);
     }
 
-    // At this point we can't cast it, set up the error object
-    let repr = obj.repr(py)
-        .and_then(|x| x.to_string(py).and_then(|y| Ok(y.into_owned())));
+    if options.datetime_as_iso8601 && is_datetime_like(py, obj)? {
+        let iso = obj.call_method(py, "isoformat", NoArgs, None)?;
+        return Ok(Value::String(iso.extract::<String>(py)?));
+    }
+
+    // At this point we can't cast it; give `default` a chance before giving up.
+    if let Some(callback) = default {
+        let result = callback.call(py, (obj,), None)?;
+        return to_json_inner(py, &result, default, options, depth + 1, seen);
+    }
+
+    let repr = obj.repr(py).and_then(|x| x.to_string(py).map(|y| y.into_owned()));
     Err(JsonError::TypeError(obj.get_type(py).name(py).into_owned(), repr))
 }
 
+/// Whether `obj` is a `datetime.datetime`, `datetime.date`, or `datetime.time` instance. All
+/// three (and only these three, of the `datetime` module's types) have an `isoformat()` method
+/// that round-trips through `datetime.*.fromisoformat`.
+fn is_datetime_like(py: Python, obj: &PyObject) -> Result<bool, JsonError> {
+    let datetime_mod = py.import("datetime")?;
+    let builtins = py.import("builtins")?;
+    for type_name in &["datetime", "date", "time"] {
+        let class = datetime_mod.get(py, type_name)?;
+        let is_instance = builtins
+            .call(py,
+                  "isinstance",
+                  PyTuple::new(py, &[obj.clone_ref(py), class]),
+                  None)?
+            .extract::<bool>(py)?;
+        if is_instance {
+            return Ok(true);
+        }
+    }
+    Ok(false)
+}
+
 /// Convert from a `serde_json::Value` to a `cpython::PyObject`.
 pub fn from_json(py: Python, json: Value) -> Result<PyObject, JsonError> {
+    from_json_with(py, json, Options::default())
+}
+
+/// Like `from_json`, but applies `options` to conversions that have more than one reasonable
+/// behavior.
+pub fn from_json_with(py: Python, json: Value, options: Options) -> Result<PyObject, JsonError> {
+    from_json_inner(py, json, options, None, StringCacheMode::None, 0)
+}
+
+/// Like `from_json_with`, but looks up every string it converts (per `mode`) in `cache` first,
+/// cloning the cached `PyObject` instead of allocating a new `PyUnicode`.
+///
+/// `cache` is designed to be reused across many calls: when rebuilding a large array of similar
+/// objects, the same dict keys (and, in `StringCacheMode::All`, the same string values) show up
+/// over and over, so paying the `PyUnicode` allocation cost once per distinct string is a real
+/// win. Use `StringCache::cache_clear` to bound how much memory it holds onto.
+pub fn from_json_cached(py: Python,
+                         json: Value,
+                         options: Options,
+                         mode: StringCacheMode,
+                         cache: &mut StringCache)
+                         -> Result<PyObject, JsonError> {
+    from_json_inner(py, json, options, Some(cache), mode, 0)
+}
+
+fn from_json_inner(py: Python,
+                    json: Value,
+                    options: Options,
+                    mut cache: Option<&mut StringCache>,
+                    mode: StringCacheMode,
+                    depth: usize)
+                    -> Result<PyObject, JsonError> {
+    if depth > options.max_depth {
+        return Err(JsonError::RecursionLimitExceeded);
+    }
+
     macro_rules! obj {
         ($x:ident) => {
             Ok($x.into_py_object(py).into_object())
         }
     }
 
+    // Reborrows `cache` so it can be used again after this call returns.
+    macro_rules! reborrow {
+        () => {
+            cache.as_mut().map(|c| &mut **c)
+        }
+    }
+
     match json {
         Value::Number(x) => {
+            // The overwhelming majority of numbers are plain machine-word integers; check those
+            // first so the common path never pays for `to_string()`. Everything past this point
+            // needs the raw text anyway (out-of-range integers, the bare
+            // `Infinity`/`-Infinity`/`NaN` tokens `json.dumps(allow_nan=True)` emits, and
+            // out-of-range floats all have to be told apart by it), so it's only computed once
+            // we already know we need it.
             if let Some(n) = x.as_u64() {
-                obj!(n)
-            } else if let Some(n) = x.as_i64() {
-                obj!(n)
-            } else if let Some(n) = x.as_f64() {
-                obj!(n)
-            } else {
-                // We should never get to this point
-                Err(JsonError::ImpossibleNumber)
+                return obj!(n);
             }
+            if let Some(n) = x.as_i64() {
+                return obj!(n);
+            }
+
+            let text = x.to_string();
+            match text.as_str() {
+                // These have no `.`/exponent, so the big-integer arm below would otherwise take
+                // them; they have to be intercepted here to respect `allow_nan`.
+                "Infinity" | "-Infinity" | "NaN" if !options.allow_nan => {
+                    Err(JsonError::InvalidFloat)
+                }
+                "Infinity" => {
+                    let value = f64::INFINITY;
+                    obj!(value)
+                }
+                "-Infinity" => {
+                    let value = f64::NEG_INFINITY;
+                    obj!(value)
+                }
+                "NaN" => {
+                    let value = f64::NAN;
+                    obj!(value)
+                }
+                _ if !text.contains('.') && !text.contains('e') && !text.contains('E') => {
+                    // Too big for u64/i64, but the lack of a `.`/exponent means this is still an
+                    // integer, not a float: it's an arbitrary-precision integer produced by the
+                    // `arbitrary_precision` feature. Building it from `text` directly keeps every
+                    // digit; going through `as_f64` here would silently round it, since every
+                    // motivating big-int example (crypto nonces, factorials, blockchain values) is
+                    // well within `f64`'s range despite not fitting in 64 bits.
+                    let builtins = py.import("builtins")?;
+                    Ok(builtins
+                           .call(py,
+                                 "int",
+                                 PyTuple::new(py, &[text.into_py_object(py).into_object()]),
+                                 None)?)
+                }
+                _ => {
+                    match x.as_f64() {
+                        Some(n) => obj!(n),
+                        // We should never get here: not an integer (handled above), and not
+                        // representable as f64 either.
+                        None => Err(JsonError::ImpossibleNumber),
+                    }
+                }
+            }
+        }
+        Value::String(x) => {
+            if options.datetime_as_iso8601 {
+                if let Some(obj) = parse_iso8601(py, &x)? {
+                    return Ok(obj);
+                }
+            }
+            Ok(if mode == StringCacheMode::All {
+                   string_obj(py, reborrow!(), &x)
+               } else {
+                   PyUnicode::new(py, &x).into_object()
+               })
         }
-        Value::String(x) => Ok(PyUnicode::new(py, &x).into_object()),
         Value::Bool(x) => obj!(x),
         Value::Array(vec) => {
             let mut elements = Vec::new();
             for item in vec {
-                elements.push(from_json(py, item)?);
+                elements.push(from_json_inner(py, item, options, reborrow!(), mode, depth + 1)?);
             }
             Ok(PyList::new(py, &elements[..]).into_object())
         }
         Value::Object(map) => {
             let dict = PyDict::new(py);
             for (key, value) in map {
-                dict.set_item(py, key, from_json(py, value)?)?;
+                let key_obj = if mode == StringCacheMode::All || mode == StringCacheMode::Keys {
+                    string_obj(py, reborrow!(), &key)
+                } else {
+                    PyUnicode::new(py, &key).into_object()
+                };
+                dict.set_item(py,
+                              key_obj,
+                              from_json_inner(py, value, options, reborrow!(), mode, depth + 1)?)?;
             }
             Ok(dict.into_object())
         }
@@ -266,6 +613,56 @@ pub fn from_json(py: Python, json: Value) -> Result<PyObject, JsonError> {
     }
 }
 
+/// Returns a `PyObject` for `text`, going through `cache` (if present) to reuse a previously
+/// interned `PyUnicode` instead of allocating a new one.
+fn string_obj(py: Python, cache: Option<&mut StringCache>, text: &str) -> PyObject {
+    match cache {
+        Some(cache) => cache.get_or_insert(py, text),
+        None => PyUnicode::new(py, text).into_object(),
+    }
+}
+
+/// Best-effort attempt to parse `text` back into a `datetime.datetime`, `datetime.date`, or
+/// `datetime.time`. Returns `None` (rather than an error) if none of them accept it, so the
+/// caller can fall back to a plain string.
+///
+/// `datetime.fromisoformat` alone isn't enough to pick the right type: it also accepts date-only
+/// text like `"2020-01-01"` (returning a midnight `datetime`), which would otherwise turn a
+/// serialized `date` back into a `datetime` on the way in. `some_datetime.isoformat()` always has a `T`
+/// separating the date and time halves, and neither `date` nor `time` text ever does, so we use
+/// that to try the type that actually matches first.
+fn parse_iso8601(py: Python, text: &str) -> Result<Option<PyObject>, JsonError> {
+    let datetime_mod = py.import("datetime")?;
+    let order: &[&str] = if text.contains('T') {
+        &["datetime", "date", "time"]
+    } else {
+        &["date", "time", "datetime"]
+    };
+    for type_name in order {
+        let class = datetime_mod.get(py, type_name)?;
+        if let Ok(obj) = class.call_method(py, "fromisoformat", (text,), None) {
+            return Ok(Some(obj));
+        }
+    }
+    Ok(None)
+}
+
+/// Parse `bytes` as JSON and convert the result straight to a `cpython::PyObject`, bridging
+/// `serde_json`'s parser with `from_json` the way the stdlib `json` module's `loads` bridges a
+/// JSON string to a Python object.
+pub fn loads(py: Python, bytes: &[u8]) -> Result<PyObject, JsonError> {
+    let value: Value = serde_json::from_slice(bytes)?;
+    from_json(py, value)
+}
+
+/// Convert a `cpython::PyObject` to a JSON string, bridging `to_json` with `serde_json`'s
+/// serializer the way the stdlib `json` module's `dumps` bridges a Python object to a JSON
+/// string.
+pub fn dumps(py: Python, obj: &PyObject) -> Result<String, JsonError> {
+    let value = to_json(py, obj)?;
+    Ok(serde_json::to_string(&value)?)
+}
+
 #[cfg(test)]
 mod tests {
     use cpython::*;
@@ -285,7 +682,7 @@ mod tests {
 
         for line in BufReader::new(&File::open("testdata/to_json.txt").unwrap()).lines() {
             let line = line.unwrap();
-            if line == "" || line.starts_with("#") {
+            if line.is_empty() || line.starts_with('#') {
                 continue;
             }
             let mut line: Vec<_> = line.split("\t").collect();
@@ -323,6 +720,31 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_bigint_roundtrip() {
+        let gil = Python::acquire_gil();
+        let py = gil.python();
+
+        // use operator.__eq__ to determine equality of PyObjects
+        let operator = py.import("operator").unwrap();
+
+        for expr in &["2 ** 128", "-(2 ** 128)", "factorial(30)"] {
+            let locals = PyDict::new(py);
+            py.run("from math import factorial", None, Some(&locals)).unwrap();
+            let obj = py.eval(expr, None, Some(&locals)).unwrap();
+
+            let json = to_json(py, &obj).unwrap();
+            let roundtripped = from_json(py, json).unwrap();
+
+            let eq = operator
+                .call(py, "__eq__", PyTuple::new(py, &[roundtripped, obj]), None)
+                .unwrap();
+            assert!(eq.extract::<bool>(py).unwrap(),
+                    "bigint did not round-trip losslessly: {}",
+                    expr);
+        }
+    }
+
     #[test]
     fn test_unserializable() {
         let gil = Python::acquire_gil();
@@ -342,6 +764,184 @@ mod tests {
         assert_eq!(err.ptraceback, None);
     }
 
+    #[test]
+    fn test_datetime_as_iso8601() {
+        let gil = Python::acquire_gil();
+        let py = gil.python();
+
+        let datetime = py.import("datetime").unwrap();
+        let min = datetime
+            .get(py, "datetime")
+            .unwrap()
+            .getattr(py, "min")
+            .unwrap();
+
+        let options = Options { datetime_as_iso8601: true, ..Options::default() };
+        let json = to_json_with(py, &min, None, options).unwrap();
+        assert_eq!(json, Value::String("0001-01-01T00:00:00".to_string()));
+
+        let roundtripped = from_json_with(py, json, options).unwrap();
+        let operator = py.import("operator").unwrap();
+        let eq = operator
+            .call(py, "__eq__", PyTuple::new(py, &[roundtripped, min]), None)
+            .unwrap();
+        assert!(eq.extract::<bool>(py).unwrap());
+    }
+
+    #[test]
+    fn test_date_and_time_as_iso8601() {
+        let gil = Python::acquire_gil();
+        let py = gil.python();
+
+        let datetime = py.import("datetime").unwrap();
+        let operator = py.import("operator").unwrap();
+        let options = Options { datetime_as_iso8601: true, ..Options::default() };
+
+        // A `date` must round-trip as a `date`, not get promoted to a `datetime` just because
+        // `datetime.fromisoformat` happens to also accept date-only text.
+        let date = datetime.get(py, "date").unwrap().getattr(py, "min").unwrap();
+        let json = to_json_with(py, &date, None, options).unwrap();
+        assert_eq!(json, Value::String("0001-01-01".to_string()));
+        let roundtripped = from_json_with(py, json, options).unwrap();
+        assert_eq!(roundtripped.get_type(py).name(py), date.get_type(py).name(py));
+        let eq = operator
+            .call(py, "__eq__", PyTuple::new(py, &[roundtripped, date]), None)
+            .unwrap();
+        assert!(eq.extract::<bool>(py).unwrap());
+
+        // A `time` must round-trip as a `time`.
+        let time = datetime.get(py, "time").unwrap().getattr(py, "min").unwrap();
+        let json = to_json_with(py, &time, None, options).unwrap();
+        assert_eq!(json, Value::String("00:00:00".to_string()));
+        let roundtripped = from_json_with(py, json, options).unwrap();
+        assert_eq!(roundtripped.get_type(py).name(py), time.get_type(py).name(py));
+        let eq = operator
+            .call(py, "__eq__", PyTuple::new(py, &[roundtripped, time]), None)
+            .unwrap();
+        assert!(eq.extract::<bool>(py).unwrap());
+    }
+
+    #[test]
+    fn test_loads_dumps() {
+        let gil = Python::acquire_gil();
+        let py = gil.python();
+
+        let obj = loads(py, br#"{"a": [1, 2, 3]}"#).unwrap();
+        let dict = obj.cast_as::<PyDict>(py).unwrap();
+        let a = dict.get_item(py, "a").unwrap();
+        let a = a.cast_as::<PyList>(py).unwrap();
+        assert_eq!(a.len(py), 3);
+
+        let json = dumps(py, &obj).unwrap();
+        assert_eq!(serde_json::from_str::<Value>(&json).unwrap(),
+                   serde_json::from_str::<Value>(r#"{"a": [1, 2, 3]}"#).unwrap());
+    }
+
+    #[test]
+    fn test_allow_nan() {
+        let gil = Python::acquire_gil();
+        let py = gil.python();
+
+        let inf = py.eval("float('inf')", None, None).unwrap();
+        assert!(matches!(to_json(py, &inf).unwrap_err(), JsonError::InvalidFloat));
+
+        let options = Options { allow_nan: true, ..Options::default() };
+        let json = to_json_with(py, &inf, None, options).unwrap();
+        // "Infinity" isn't valid JSON number syntax, so it can't be produced by parsing (there's
+        // no `serde_json::from_str::<Value>("Infinity")` to compare against); check the `Number`
+        // holds the bare token directly instead.
+        match &json {
+            Value::Number(n) => assert_eq!(n.to_string(), "Infinity"),
+            _ => panic!("expected a Number, got {:?}", json),
+        }
+
+        let roundtripped = from_json_with(py, json, options).unwrap();
+        assert_eq!(roundtripped.extract::<f64>(py).unwrap(), f64::INFINITY);
+
+        let nan = Value::Number(serde_json::Number::from_string_unchecked("NaN".to_owned()));
+        assert!(matches!(from_json_with(py, nan, Options::default()).unwrap_err(),
+                         JsonError::InvalidFloat));
+    }
+
+    #[test]
+    fn test_string_cache() {
+        let gil = Python::acquire_gil();
+        let py = gil.python();
+
+        let json: Value = serde_json::from_str(r#"[{"a": "x"}, {"a": "y"}]"#).unwrap();
+        let mut cache = StringCache::new();
+        let obj = from_json_cached(py, json, Options::default(), StringCacheMode::All, &mut cache)
+            .unwrap();
+
+        // "a" is cached as a dict key for both elements, plus "x" and "y" as values.
+        assert_eq!(cache.cache_usage(), 3);
+
+        let list = obj.cast_as::<PyList>(py).unwrap();
+        let first_dict = list.get_item(py, 0);
+        let first_dict = first_dict.cast_as::<PyDict>(py).unwrap();
+        let second_dict = list.get_item(py, 1);
+        let second_dict = second_dict.cast_as::<PyDict>(py).unwrap();
+        let first_key = first_dict.items(py)[0].0.clone_ref(py);
+        let second_key = second_dict.items(py)[0].0.clone_ref(py);
+        assert!(first_key.as_ptr() == second_key.as_ptr());
+
+        cache.cache_clear();
+        assert_eq!(cache.cache_usage(), 0);
+    }
+
+    #[test]
+    fn test_circular_reference() {
+        let gil = Python::acquire_gil();
+        let py = gil.python();
+
+        let locals = PyDict::new(py);
+        py.run("l = []; l.append(l)", None, Some(&locals)).unwrap();
+        let l = locals.get_item(py, "l").unwrap();
+
+        match to_json(py, &l).unwrap_err() {
+            JsonError::CircularReference(obj) => assert!(obj == l),
+            err => panic!("expected CircularReference, got {:?}", err),
+        }
+    }
+
+    #[test]
+    fn test_max_depth() {
+        let gil = Python::acquire_gil();
+        let py = gil.python();
+
+        let nested = py.eval("[[[1]]]", None, None).unwrap();
+
+        // Three levels of nesting is well within the default limit.
+        assert!(to_json(py, &nested).is_ok());
+
+        // Lowering Options::max_depth makes the same object exceed it.
+        let options = Options { max_depth: 1, ..Options::default() };
+        assert!(matches!(to_json_with(py, &nested, None, options).unwrap_err(),
+                         JsonError::RecursionLimitExceeded));
+    }
+
+    #[test]
+    fn test_to_pyerr_recursion_limit_exceeded() {
+        let gil = Python::acquire_gil();
+        let py = gil.python();
+
+        let err = JsonError::RecursionLimitExceeded.to_pyerr(py);
+        assert_eq!(err.ptype, cpython::exc::ValueError::type_object(py).into_object());
+        assert_eq!(err.pvalue.unwrap().to_string(), "maximum recursion depth exceeded");
+        assert_eq!(err.ptraceback, None);
+    }
+
+    #[test]
+    fn test_to_pyerr_circular_reference() {
+        let gil = Python::acquire_gil();
+        let py = gil.python();
+
+        let err = JsonError::CircularReference(py.None()).to_pyerr(py);
+        assert_eq!(err.ptype, cpython::exc::ValueError::type_object(py).into_object());
+        assert_eq!(err.pvalue.unwrap().to_string(), "Circular reference detected");
+        assert_eq!(err.ptraceback, None);
+    }
+
     #[test]
     /// The compiler already makes sure that JsonError can derive Debug, but kcov doesn't know
     /// that. This makes JsonError's #[derive(Debug)] show as covered code.